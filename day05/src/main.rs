@@ -17,13 +17,13 @@ fn main() {
     let mut machine = IntcodeMachine::new(tape.clone())
         .with_input(1);
 
-    let output = machine.run();
+    let output = machine.run().expect("Part 1 program should run to completion");
     println!("Part 1: {}", output[0]);
 
     // Part 2
     let mut machine = IntcodeMachine::new(tape)
         .with_input(5);
 
-    let output = machine.run();
+    let output = machine.run().expect("Part 2 program should run to completion");
     println!("Part 2: {}", output[0]);
 }
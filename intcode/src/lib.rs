@@ -1,21 +1,73 @@
+use std::cell::RefCell;
 use std::collections::vec_deque::VecDeque;
-
-pub struct IntcodeMachine {
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+pub mod network;
+
+/// `Clone` is a checkpoint primitive for the default `IntcodeMachine<VecDeque<i64>,
+/// Vec<i64>>`: cloning snapshots its tape, position, relative base, input,
+/// output and status, so a search or backtracking puzzle can try an input
+/// path against the clone and fall back to the original if it doesn't pan
+/// out.
+///
+/// That doesn't hold once `I`/`O` is `Pipe`, as for every `Amplifier`/
+/// `Network` machine in this crate: `Pipe`'s derived `Clone` just bumps an
+/// `Rc` refcount, so the clone's input and output still alias the
+/// original's. Cloning a `Pipe`-backed machine does not give you an
+/// independent snapshot.
+#[derive(Clone)]
+pub struct IntcodeMachine<I = VecDeque<i64>, O = Vec<i64>> {
     tape: Vec<i64>,
     position: usize,
     relative_base: isize,
-    input: VecDeque<i64>,
-    output: Vec<i64>,
+    input: I,
+    output: O,
     status: MachineStatus,
 }
 
-#[derive(Clone, Copy, Eq, PartialEq)]
-enum ParameterMode {
+/// An addressing mode for a single instruction parameter.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mode {
     Positional,
     Immediate,
     Relative,
 }
 
+/// A decoded instruction, with its parameters' addressing modes resolved
+/// but not yet read from the tape. Shared by `step`, which
+/// dispatches on it, and `disassemble`, which renders it, so the two never
+/// disagree about instruction layout.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Instruction {
+    Add(Mode, Mode, Mode),
+    Multiply(Mode, Mode, Mode),
+    Input(Mode),
+    Output(Mode),
+    JumpIfTrue(Mode, Mode),
+    JumpIfFalse(Mode, Mode),
+    LessThan(Mode, Mode, Mode),
+    Equals(Mode, Mode, Mode),
+    AdjustRelativeBase(Mode),
+    Halt,
+}
+
+impl Instruction {
+    /// Number of tape cells this instruction occupies, including the opcode itself.
+    fn len(&self) -> usize {
+        match self {
+            Instruction::Add(..)
+            | Instruction::Multiply(..)
+            | Instruction::LessThan(..)
+            | Instruction::Equals(..) => 4,
+            Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..) => 3,
+            Instruction::Input(..) | Instruction::Output(..) | Instruction::AdjustRelativeBase(..) => 2,
+            Instruction::Halt => 1,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum MachineStatus {
     Run,
@@ -23,101 +75,305 @@ enum MachineStatus {
     Halt,
 }
 
-impl IntcodeMachine {
-    pub fn new(tape: Vec<i64>) -> IntcodeMachine {
+/// Everything that can go wrong while decoding or executing an instruction.
+///
+/// Unknown opcodes and modes, running off the tape, negative addresses and
+/// stepping a halted machine are surfaced through this enum instead of a
+/// panic, so a caller embedding the machine (a server, a fuzzer, ...) can
+/// recover from them. Arithmetic overflow and addresses too large to
+/// allocate are not covered: those still panic or abort.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ExecutionError {
+    /// The opcode (instruction value modulo 100) has no known instruction.
+    UnknownOpcode(i64),
+    /// A parameter mode digit was neither 0, 1 nor 2.
+    UnknownMode(u8),
+    /// Execution ran off the end of the tape.
+    InvalidAddress(usize),
+    /// A computed address (relative or positional) was negative.
+    NegativeAddress(isize),
+    /// `run`/`run_for_target` was called again after the machine halted.
+    AlreadyHalted,
+    /// A `Network` machine produced output that didn't come in a complete
+    /// `(dest, x, y)` packet, e.g. it halted or yielded mid-packet.
+    IncompletePacket,
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecutionError::UnknownOpcode(opcode) => write!(f, "unknown opcode {}", opcode),
+            ExecutionError::UnknownMode(mode) => write!(f, "unknown parameter mode {}", mode),
+            ExecutionError::InvalidAddress(position) => {
+                write!(f, "execution ran off the end of the tape at position {}", position)
+            }
+            ExecutionError::NegativeAddress(address) => {
+                write!(f, "computed a negative address {}", address)
+            }
+            ExecutionError::AlreadyHalted => write!(f, "machine has already halted"),
+            ExecutionError::IncompletePacket => write!(f, "output ended mid-packet"),
+        }
+    }
+}
+
+impl Error for ExecutionError {}
+
+/// A source of input values for an `IntcodeMachine`, read by opcode 3.
+pub trait Input {
+    fn read(&mut self) -> Option<i64>;
+}
+
+/// A sink for output values produced by an `IntcodeMachine`, written by opcode 4.
+pub trait Output {
+    fn write(&mut self, value: i64);
+}
+
+impl Input for VecDeque<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl Output for Vec<i64> {
+    fn write(&mut self, value: i64) {
+        self.push(value);
+    }
+}
+
+/// A reference-counted FIFO queue that is both an `Input` and an `Output`.
+///
+/// Cloning a `Pipe` gives another handle to the same underlying queue, so the
+/// output of one machine can be wired directly into the input of another
+/// without the caller shuttling values between `run()` calls.
+#[derive(Clone)]
+pub struct Pipe(Rc<RefCell<VecDeque<i64>>>);
+
+impl Pipe {
+    pub fn new() -> Pipe {
+        Pipe(Rc::new(RefCell::new(VecDeque::new())))
+    }
+
+    pub fn push(&self, value: i64) {
+        self.0.borrow_mut().push_back(value);
+    }
+
+    pub fn pop(&self) -> Option<i64> {
+        self.0.borrow_mut().pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+}
+
+impl Default for Pipe {
+    fn default() -> Pipe {
+        Pipe::new()
+    }
+}
+
+impl Input for Pipe {
+    fn read(&mut self) -> Option<i64> {
+        self.pop()
+    }
+}
+
+impl Output for Pipe {
+    fn write(&mut self, value: i64) {
+        self.push(value);
+    }
+}
+
+impl<I: Input, O: Output> IntcodeMachine<I, O> {
+    /// Builds a machine wired to a caller-supplied input and output, e.g. a
+    /// pair of `Pipe`s shared with a neighbouring machine.
+    pub fn with_io(tape: Vec<i64>, input: I, output: O) -> IntcodeMachine<I, O> {
         IntcodeMachine {
             tape,
             position: 0,
-            input: VecDeque::new(),
-            output: vec![],
-            status: MachineStatus::Run,
             relative_base: 0,
+            input,
+            output,
+            status: MachineStatus::Run,
         }
     }
 
-    pub fn with_zeroth(mut self, value: i64) -> IntcodeMachine {
+    pub fn with_zeroth(mut self, value: i64) -> IntcodeMachine<I, O> {
         self.tape[0] = value;
         return self;
     }
 
-    pub fn with_init(mut self, noun: i64, verb: i64) -> IntcodeMachine {
+    pub fn with_init(mut self, noun: i64, verb: i64) -> IntcodeMachine<I, O> {
         self.tape[1] = noun;
         self.tape[2] = verb;
         return self;
     }
 
-    pub fn with_input(mut self, input: i64) -> Self {
-        self.add_input(input);
-        return self;
-    }
-
-    pub fn with_inputs(mut self, input: &VecDeque<i64>) -> Self {
-        self.add_inputs(input);
-        return self;
+    fn parse_mode(&self, i: i64) -> Result<Mode, ExecutionError> {
+        match i {
+            0 => Ok(Mode::Positional),
+            1 => Ok(Mode::Immediate),
+            2 => Ok(Mode::Relative),
+            _ => Err(ExecutionError::UnknownMode(i as u8)),
+        }
     }
 
-    pub fn add_inputs(&mut self, input: &VecDeque<i64>) {
-        input.iter().for_each(|i| self.add_input(*i));
-    }
+    /// Decodes the instruction at `position` without mutating any machine
+    /// state (position, relative base, tape, ...). Used both by
+    /// `step`, to dispatch, and by `disassemble`, to render.
+    pub fn decode(&self, position: usize) -> Result<Instruction, ExecutionError> {
+        if position >= self.tape.len() {
+            return Err(ExecutionError::InvalidAddress(position));
+        }
 
-    pub fn add_input(&mut self, input: i64) {
-        if self.status != MachineStatus::Halt {
-            self.status = MachineStatus::Run;
-            self.input.push_back(input);
+        let value = self.tape[position];
+        let opcode = value % 100;
+        let mode1 = self.parse_mode(value / 100 % 10)?;
+        let mode2 = self.parse_mode(value / 1000 % 10)?;
+        let mode3 = self.parse_mode(value / 10000 % 10)?;
+
+        match opcode {
+            1 => Ok(Instruction::Add(mode1, mode2, mode3)),
+            2 => Ok(Instruction::Multiply(mode1, mode2, mode3)),
+            3 => Ok(Instruction::Input(mode1)),
+            4 => Ok(Instruction::Output(mode1)),
+            5 => Ok(Instruction::JumpIfTrue(mode1, mode2)),
+            6 => Ok(Instruction::JumpIfFalse(mode1, mode2)),
+            7 => Ok(Instruction::LessThan(mode1, mode2, mode3)),
+            8 => Ok(Instruction::Equals(mode1, mode2, mode3)),
+            9 => Ok(Instruction::AdjustRelativeBase(mode1)),
+            99 => Ok(Instruction::Halt),
+            _ => Err(ExecutionError::UnknownOpcode(opcode)),
         }
     }
 
-    fn parse_mode(&self, i: i64) -> ParameterMode {
-        match i {
-            1 => ParameterMode::Immediate,
-            2 => ParameterMode::Relative,
-            0 | _ => ParameterMode::Positional,
+    /// Walks the tape from position 0, decoding and rendering one
+    /// instruction at a time, e.g. `ADD [10] [20] -> [30]`. Stretches of
+    /// tape that don't decode to a known instruction (typically data rather
+    /// than code) are rendered as raw values instead of causing a failure.
+    pub fn disassemble(&self) -> Vec<(usize, String)> {
+        let mut listing = Vec::new();
+        let mut position = 0;
+
+        while position < self.tape.len() {
+            match self.decode(position) {
+                Ok(instruction) => {
+                    listing.push((position, Self::render(&self.tape, position, instruction)));
+                    position += instruction.len();
+                }
+                Err(_) => {
+                    listing.push((position, format!("DATA {}", self.tape[position])));
+                    position += 1;
+                }
+            }
         }
-    }
 
-    fn fetch1mode(&mut self) -> ParameterMode {
-        let parameter_mode = self.tape[self.position] / 100;
-        self.parse_mode(parameter_mode % 10)
+        listing
     }
 
-    fn fetch2modes(&mut self) -> (ParameterMode, ParameterMode) {
-        let mode1 = self.fetch1mode();
-        let mode2 = self.tape[self.position] / 1000;
+    fn render_operand(tape: &[i64], position: usize, mode: Mode) -> String {
+        let raw = tape.get(position).copied().unwrap_or(0);
 
-        (self.parse_mode(mode2 % 10), mode1)
+        match mode {
+            Mode::Positional => format!("[{}]", raw),
+            Mode::Immediate => format!("#{}", raw),
+            Mode::Relative => format!("rel[base{:+}]", raw),
+        }
     }
 
-    fn fetch3modes(&mut self) -> (ParameterMode, ParameterMode, ParameterMode) {
-        let (mode2, mode1) = self.fetch2modes();
-        let mode3 = self.tape[self.position] / 10000;
-
-        (self.parse_mode(mode3 % 10), mode2, mode1)
+    fn render(tape: &[i64], position: usize, instruction: Instruction) -> String {
+        match instruction {
+            Instruction::Add(m1, m2, m3) => format!(
+                "ADD {} {} -> {}",
+                Self::render_operand(tape, position + 1, m1),
+                Self::render_operand(tape, position + 2, m2),
+                Self::render_operand(tape, position + 3, m3),
+            ),
+            Instruction::Multiply(m1, m2, m3) => format!(
+                "MUL {} {} -> {}",
+                Self::render_operand(tape, position + 1, m1),
+                Self::render_operand(tape, position + 2, m2),
+                Self::render_operand(tape, position + 3, m3),
+            ),
+            Instruction::Input(m1) => format!("IN -> {}", Self::render_operand(tape, position + 1, m1)),
+            Instruction::Output(m1) => format!("OUT {}", Self::render_operand(tape, position + 1, m1)),
+            Instruction::JumpIfTrue(m1, m2) => format!(
+                "JNZ {} {}",
+                Self::render_operand(tape, position + 1, m1),
+                Self::render_operand(tape, position + 2, m2),
+            ),
+            Instruction::JumpIfFalse(m1, m2) => format!(
+                "JZ {} {}",
+                Self::render_operand(tape, position + 1, m1),
+                Self::render_operand(tape, position + 2, m2),
+            ),
+            Instruction::LessThan(m1, m2, m3) => format!(
+                "LT {} {} -> {}",
+                Self::render_operand(tape, position + 1, m1),
+                Self::render_operand(tape, position + 2, m2),
+                Self::render_operand(tape, position + 3, m3),
+            ),
+            Instruction::Equals(m1, m2, m3) => format!(
+                "EQ {} {} -> {}",
+                Self::render_operand(tape, position + 1, m1),
+                Self::render_operand(tape, position + 2, m2),
+                Self::render_operand(tape, position + 3, m3),
+            ),
+            Instruction::AdjustRelativeBase(Mode::Immediate) => {
+                format!("ARB {:+}", tape.get(position + 1).copied().unwrap_or(0))
+            }
+            Instruction::AdjustRelativeBase(m1) => {
+                format!("ARB {}", Self::render_operand(tape, position + 1, m1))
+            }
+            Instruction::Halt => "HLT".to_string(),
+        }
     }
 
-    fn fetch_arg(&mut self, mode: ParameterMode) -> i64 {
+    fn fetch_arg(&mut self, mode: Mode) -> Result<i64, ExecutionError> {
         self.position += 1;
 
-        let pointer: usize = match mode {
-            ParameterMode::Positional => self.tape[self.position] as usize,
-            ParameterMode::Immediate => self.position,
-            ParameterMode::Relative => (self.relative_base + self.tape[self.position] as isize) as usize,
+        if self.position >= self.tape.len() {
+            return Err(ExecutionError::InvalidAddress(self.position));
+        }
+
+        let pointer: isize = match mode {
+            Mode::Positional => self.tape[self.position] as isize,
+            Mode::Immediate => self.position as isize,
+            Mode::Relative => self.relative_base + self.tape[self.position] as isize,
         };
 
+        if pointer < 0 {
+            return Err(ExecutionError::NegativeAddress(pointer));
+        }
+
+        let pointer = pointer as usize;
         if pointer >= self.tape.len() {
             self.tape.resize(pointer * 2, 0);
         }
 
-        return self.tape[pointer];
+        Ok(self.tape[pointer])
     }
 
-    fn fetch_dest(&mut self, mode: ParameterMode) -> usize {
+    fn fetch_dest(&mut self, mode: Mode) -> Result<usize, ExecutionError> {
         match mode {
-            ParameterMode::Positional | ParameterMode::Immediate => {
-                self.fetch_arg(ParameterMode::Immediate) as usize
+            Mode::Positional | Mode::Immediate => {
+                let dest = self.fetch_arg(Mode::Immediate)? as isize;
+
+                if dest < 0 {
+                    return Err(ExecutionError::NegativeAddress(dest));
+                }
+
+                Ok(dest as usize)
             }
-            ParameterMode::Relative => {
-                let arg = self.fetch_arg(ParameterMode::Immediate) as isize;
-                (self.relative_base + arg) as usize
+            Mode::Relative => {
+                let arg = self.fetch_arg(Mode::Immediate)? as isize;
+                let dest = self.relative_base + arg;
+
+                if dest < 0 {
+                    return Err(ExecutionError::NegativeAddress(dest));
+                }
+
+                Ok(dest as usize)
             }
         }
     }
@@ -133,38 +389,37 @@ impl IntcodeMachine {
     /// Adds together numbers read from two positions and stores the result in a third position.
     /// For example, if your Intcode computer encounters 1,10,20,30, it should read the values at positions 10 and 20,
     /// add those values, and then overwrite the value at position 30 with their sum.
-    fn add(&mut self) {
-        let (mode3, mode2, mode1) = self.fetch3modes();
-        let a = self.fetch_arg(mode1);
-        let b = self.fetch_arg(mode2);
-        let dest = self.fetch_dest(mode3);
+    fn add(&mut self, mode1: Mode, mode2: Mode, mode3: Mode) -> Result<(), ExecutionError> {
+        let a = self.fetch_arg(mode1)?;
+        let b = self.fetch_arg(mode2)?;
+        let dest = self.fetch_dest(mode3)?;
 
         let result = a + b;
         self.store(dest, result);
         self.position += 1;
+        Ok(())
     }
 
     /// Multiply instruction, opcode 2.
     /// Multiplies the two inputs it receives and store the result in the third position.
-    fn mul(&mut self) {
-        let (mode3, mode2, mode1) = self.fetch3modes();
-        let a = self.fetch_arg(mode1);
-        let b = self.fetch_arg(mode2);
-        let dest = self.fetch_dest(mode3);
+    fn mul(&mut self, mode1: Mode, mode2: Mode, mode3: Mode) -> Result<(), ExecutionError> {
+        let a = self.fetch_arg(mode1)?;
+        let b = self.fetch_arg(mode2)?;
+        let dest = self.fetch_dest(mode3)?;
 
         let result = a * b;
         self.store(dest, result);
         self.position += 1;
+        Ok(())
     }
 
     /// Store instruction, opcode 3.
     /// Takes a single integer as input and saves it to the position given by its only parameter.
     /// For example, the instruction 3,50 would take an input value and store it at address 50.
-    fn st(&mut self) {
-        let mode = self.fetch1mode();
-        let dest = self.fetch_dest(mode);
+    fn st(&mut self, mode: Mode) -> Result<(), ExecutionError> {
+        let dest = self.fetch_dest(mode)?;
 
-        if let Some(input) = self.input.pop_front() {
+        if let Some(input) = self.input.read() {
             self.store(dest, input);
             self.position += 1;
         } else {
@@ -172,75 +427,82 @@ impl IntcodeMachine {
             self.status = MachineStatus::Yield;
             self.position -= 1;
         }
+        Ok(())
     }
 
     /// Load instruction, opcode 4.
     /// Outputs the value of its only parameter.
     /// For example, the instruction 4,50 would output the value at address 50.
-    fn ld(&mut self) {
-        let mode = self.fetch1mode();
-        let output = self.fetch_arg(mode);
+    fn ld(&mut self, mode: Mode) -> Result<i64, ExecutionError> {
+        let output = self.fetch_arg(mode)?;
 
-        self.output.push(output);
+        self.output.write(output);
         self.position += 1;
+        Ok(output)
     }
 
     /// Jump if not zero instruction, opcode 5.
     /// If the first parameter is non-zero, it sets the instruction pointer
     /// to the value from the second parameter. Otherwise, it does nothing.
-    fn jnz(&mut self) {
-        let (mode2, mode1) = self.fetch2modes();
-        let a = self.fetch_arg(mode1);
-        let b = self.fetch_arg(mode2) as usize;
+    fn jnz(&mut self, mode1: Mode, mode2: Mode) -> Result<(), ExecutionError> {
+        let a = self.fetch_arg(mode1)?;
+        let b = self.fetch_arg(mode2)?;
 
         if a != 0 {
-            self.position = b;
+            if b < 0 {
+                return Err(ExecutionError::NegativeAddress(b as isize));
+            }
+            self.position = b as usize;
         } else {
             self.position += 1;
         }
+        Ok(())
     }
 
     /// Jump if zero instruction, opcode 6.
     /// If the first parameter is zero, it sets the instruction pointer
     /// to the value from the second parameter. Otherwise, it does nothing.
-    fn jz(&mut self) {
-        let (mode2, mode1) = self.fetch2modes();
-        let a = self.fetch_arg(mode1);
-        let b = self.fetch_arg(mode2) as usize;
+    fn jz(&mut self, mode1: Mode, mode2: Mode) -> Result<(), ExecutionError> {
+        let a = self.fetch_arg(mode1)?;
+        let b = self.fetch_arg(mode2)?;
 
         if a == 0 {
-            self.position = b;
+            if b < 0 {
+                return Err(ExecutionError::NegativeAddress(b as isize));
+            }
+            self.position = b as usize;
         } else {
             self.position += 1;
         }
+        Ok(())
     }
 
     /// Test if less than instruction, opcode 7.
     /// If the first parameter is less than the second parameter, it stores 1 in the position given
     /// by the third parameter. Otherwise, it stores 0.
-    fn tlt(&mut self) {
-        let (mode3, mode2, mode1) = self.fetch3modes();
-        let a = self.fetch_arg(mode1);
-        let b = self.fetch_arg(mode2);
-        let dest = self.fetch_dest(mode3);
+    fn tlt(&mut self, mode1: Mode, mode2: Mode, mode3: Mode) -> Result<(), ExecutionError> {
+        let a = self.fetch_arg(mode1)?;
+        let b = self.fetch_arg(mode2)?;
+        let dest = self.fetch_dest(mode3)?;
 
         let result = if a < b { 1 } else { 0 };
         self.store(dest, result);
         self.position += 1;
+        Ok(())
     }
 
     /// Test if equals instruction, opcode 8.
     /// If the first parameter is equal to the second parameter, it stores 1 in the position given
     /// by the third parameter. Otherwise, it stores 0.
-    fn teq(&mut self) {
-        let (mode3, mode2, mode1) = self.fetch3modes();
-        let a = self.fetch_arg(mode1);
-        let b = self.fetch_arg(mode2);
-        let dest = self.fetch_dest(mode3);
+    fn teq(&mut self, mode1: Mode, mode2: Mode, mode3: Mode) -> Result<(), ExecutionError> {
+        let a = self.fetch_arg(mode1)?;
+        let b = self.fetch_arg(mode2)?;
+        let dest = self.fetch_dest(mode3)?;
 
         let result = if a == b { 1 } else { 0 };
         self.store(dest, result);
         self.position += 1;
+        Ok(())
     }
 
     /// Relative base adjustment instruction, opcode 9.
@@ -248,18 +510,19 @@ impl IntcodeMachine {
     /// The relative base increases (or decreases, if the value is negative) by the value of the parameter.
     /// For example, if the relative base is 2000, then after the instruction 109,19, the relative base would be 2019.
     /// If the next instruction were 204,-34, then the value at address 1985 would be output.
-    fn rel(&mut self) {
-        let mode = self.fetch1mode();
-        let base = self.fetch_arg(mode);
+    fn rel(&mut self, mode: Mode) -> Result<(), ExecutionError> {
+        let base = self.fetch_arg(mode)?;
 
         self.relative_base += base as isize;
         self.position += 1;
+        Ok(())
     }
 
     /// Halt instruction, opcode 99.
     /// This instruction signals end of execution and that the machine should exit immediately.
-    fn halt(&mut self) {
+    fn halt(&mut self) -> Result<(), ExecutionError> {
         self.status = MachineStatus::Halt;
+        Ok(())
     }
 
     pub fn halted(&self) -> bool {
@@ -270,52 +533,199 @@ impl IntcodeMachine {
         return self.status == MachineStatus::Yield;
     }
 
+    /// Executes exactly one instruction and reports what happened, instead
+    /// of running to completion. This lets a caller interleave its own
+    /// logic between instructions (e.g. react to each `Output` immediately)
+    /// rather than draining the whole output buffer after the fact.
+    pub fn step(&mut self) -> Result<StepResult, ExecutionError> {
+        if self.status == MachineStatus::Halt {
+            return Err(ExecutionError::AlreadyHalted);
+        }
+
+        self.status = MachineStatus::Run;
+
+        let instruction = self.decode(self.position)?;
+
+        if let Instruction::Output(mode) = instruction {
+            let value = self.ld(mode)?;
+            return Ok(StepResult::Output(value));
+        }
+
+        match instruction {
+            Instruction::Add(m1, m2, m3) => self.add(m1, m2, m3),
+            Instruction::Multiply(m1, m2, m3) => self.mul(m1, m2, m3),
+            Instruction::Input(m) => self.st(m),
+            Instruction::JumpIfTrue(m1, m2) => self.jnz(m1, m2),
+            Instruction::JumpIfFalse(m1, m2) => self.jz(m1, m2),
+            Instruction::LessThan(m1, m2, m3) => self.tlt(m1, m2, m3),
+            Instruction::Equals(m1, m2, m3) => self.teq(m1, m2, m3),
+            Instruction::AdjustRelativeBase(m) => self.rel(m),
+            Instruction::Halt => self.halt(),
+            Instruction::Output(_) => unreachable!("handled above"),
+        }?;
+
+        if self.status == MachineStatus::Halt {
+            Ok(StepResult::Halted)
+        } else if self.status == MachineStatus::Yield {
+            Ok(StepResult::NeedsInput)
+        } else {
+            Ok(StepResult::Continue)
+        }
+    }
+
+    pub fn run_for_target(&mut self, target: usize) -> Result<i64, ExecutionError> {
+        loop {
+            match self.step()? {
+                StepResult::Continue | StepResult::Output(_) => continue,
+                StepResult::NeedsInput | StepResult::Halted => return Ok(self.tape[target]),
+            }
+        }
+    }
+
+    /// Runs until the machine can make no further progress, and reports
+    /// whether that was because it halted or because it is waiting on
+    /// `Input::read` to return `Some`. It stops at the same points as
+    /// `run_for_target`, but returns which of the two it was instead of a
+    /// value read from the tape.
+    pub fn run_until_blocked(&mut self) -> Result<RunOutcome, ExecutionError> {
+        loop {
+            match self.step()? {
+                StepResult::Continue | StepResult::Output(_) => continue,
+                StepResult::NeedsInput => return Ok(RunOutcome::NeedsInput),
+                StepResult::Halted => return Ok(RunOutcome::Halted),
+            }
+        }
+    }
+}
+
+/// Why `run_until_blocked` returned control to the caller.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RunOutcome {
+    NeedsInput,
+    Halted,
+}
+
+/// The outcome of a single `step`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StepResult {
+    /// An instruction ran that produced neither output nor a stop condition.
+    Continue,
+    /// An `Output` instruction ran, producing this value.
+    Output(i64),
+    /// An `Input` instruction ran with nothing available to read.
+    NeedsInput,
+    /// A `Halt` instruction ran.
+    Halted,
+}
+
+impl IntcodeMachine<VecDeque<i64>, Vec<i64>> {
+    pub fn new(tape: Vec<i64>) -> IntcodeMachine {
+        IntcodeMachine {
+            tape,
+            position: 0,
+            input: VecDeque::new(),
+            output: vec![],
+            status: MachineStatus::Run,
+            relative_base: 0,
+        }
+    }
+
+    pub fn with_input(mut self, input: i64) -> Self {
+        self.add_input(input);
+        return self;
+    }
+
+    pub fn with_inputs(mut self, input: &VecDeque<i64>) -> Self {
+        self.add_inputs(input);
+        return self;
+    }
+
+    pub fn add_inputs(&mut self, input: &VecDeque<i64>) {
+        input.iter().for_each(|i| self.add_input(*i));
+    }
+
+    pub fn add_input(&mut self, input: i64) {
+        if self.status != MachineStatus::Halt {
+            self.status = MachineStatus::Run;
+            self.input.push_back(input);
+        }
+    }
+
     pub fn has_output(&self) -> bool {
         return self.output.len() > 0;
     }
 
-    pub fn run(&mut self) -> Vec<i64> {
-        self.run_for_target(0);
-        return self.output.clone();
+    pub fn run(&mut self) -> Result<Vec<i64>, ExecutionError> {
+        self.output.clear();
+        self.run_for_target(0)?;
+        return Ok(self.output.clone());
     }
+}
 
-    pub fn run_for_target(&mut self, target: usize) -> i64 {
-        self.status = MachineStatus::Run;
-        self.output.clear();
+/// A day-7-style chain of amplifiers: N identical programs, each given a
+/// phase setting and wired output-to-input with `Pipe`s so a signal flows
+/// straight through the chain without the caller shuttling values by hand.
+///
+/// When `feedback_loop` is set, the last amplifier's output feeds back into
+/// the first amplifier's input instead of draining to a final pipe, so the
+/// chain can be run repeatedly until every machine halts.
+pub struct Amplifier {
+    machines: Vec<IntcodeMachine<Pipe, Pipe>>,
+    pipes: Vec<Pipe>,
+    feedback_loop: bool,
+}
 
-        loop {
-            let opcode = self.tape[self.position] % 100;
-            match opcode {
-                1 => self.add(),
-                2 => self.mul(),
-                3 => self.st(),
-                4 => self.ld(),
-                5 => self.jnz(),
-                6 => self.jz(),
-                7 => self.tlt(),
-                8 => self.teq(),
-                9 => self.rel(),
-                99 => self.halt(),
-                _ => panic!("Unknown opcode {} at position {}", opcode, self.position),
-            }
+impl Amplifier {
+    pub fn new(tape: &[i64], phases: &[i64], feedback_loop: bool) -> Amplifier {
+        let pipe_count = if feedback_loop { phases.len() } else { phases.len() + 1 };
+        let pipes: Vec<Pipe> = (0..pipe_count).map(|_| Pipe::new()).collect();
+
+        for (i, &phase) in phases.iter().enumerate() {
+            pipes[i].push(phase);
+        }
+
+        let machines = (0..phases.len())
+            .map(|i| {
+                let input = pipes[i].clone();
+                let output_index = if feedback_loop { (i + 1) % phases.len() } else { i + 1 };
+                let output = pipes[output_index].clone();
+
+                IntcodeMachine::with_io(tape.to_vec(), input, output)
+            })
+            .collect();
+
+        Amplifier { machines, pipes, feedback_loop }
+    }
 
-            if self.status == MachineStatus::Halt || self.status == MachineStatus::Yield {
-                return self.tape[target];
+    /// Runs the chain with `initial_signal` fed into the first amplifier,
+    /// round-robining every machine until all of them have halted, and
+    /// returns the last signal produced by the chain.
+    pub fn run(&mut self, initial_signal: i64) -> Result<i64, ExecutionError> {
+        self.pipes[0].push(initial_signal);
+
+        while self.machines.iter().any(|machine| !machine.halted()) {
+            for machine in self.machines.iter_mut() {
+                if !machine.halted() {
+                    machine.run_for_target(0)?;
+                }
             }
         }
+
+        let final_pipe = if self.feedback_loop { 0 } else { self.pipes.len() - 1 };
+        Ok(self.pipes[final_pipe].pop().unwrap_or(initial_signal))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::IntcodeMachine;
+    use super::{Amplifier, ExecutionError, Instruction, IntcodeMachine, Mode, StepResult};
 
     #[test]
     fn test_mul_should_output_3500() {
         let tape: Vec<i64> = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
         let mut machine = IntcodeMachine::new(tape);
 
-        assert_eq!(machine.run_for_target(0), 3500)
+        assert_eq!(machine.run_for_target(0).unwrap(), 3500)
     }
 
     #[test]
@@ -323,7 +733,7 @@ mod tests {
         let tape: Vec<i64> = vec![1, 0, 0, 0, 99];
         let mut machine = IntcodeMachine::new(tape);
 
-        assert_eq!(machine.run_for_target(0), 2)
+        assert_eq!(machine.run_for_target(0).unwrap(), 2)
     }
 
     #[test]
@@ -331,7 +741,7 @@ mod tests {
         let tape: Vec<i64> = vec![2, 3, 0, 3, 99];
         let mut machine = IntcodeMachine::new(tape);
 
-        assert_eq!(machine.run_for_target(3), 6)
+        assert_eq!(machine.run_for_target(3).unwrap(), 6)
     }
 
     #[test]
@@ -339,7 +749,7 @@ mod tests {
         let tape: Vec<i64> = vec![2, 4, 4, 5, 99, 0];
         let mut machine = IntcodeMachine::new(tape);
 
-        assert_eq!(machine.run_for_target(5), 9801)
+        assert_eq!(machine.run_for_target(5).unwrap(), 9801)
     }
 
     #[test]
@@ -347,7 +757,7 @@ mod tests {
         let tape: Vec<i64> = vec![1, 1, 1, 4, 99, 5, 6, 0, 99];
         let mut machine = IntcodeMachine::new(tape);
 
-        assert_eq!(machine.run_for_target(0), 30)
+        assert_eq!(machine.run_for_target(0).unwrap(), 30)
     }
 
     #[test]
@@ -355,7 +765,7 @@ mod tests {
         let tape: Vec<i64> = vec![1002, 4, 3, 4, 33];
         let mut machine = IntcodeMachine::new(tape);
 
-        assert_eq!(machine.run_for_target(4), 99)
+        assert_eq!(machine.run_for_target(4).unwrap(), 99)
     }
 
     #[test]
@@ -364,8 +774,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(1234);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1234);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1234);
     }
 
     #[test]
@@ -374,8 +784,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(8);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1);
     }
 
     #[test]
@@ -384,8 +794,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(5);
 
-        machine.run();
-        assert_eq!(machine.output[0], 0);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 0);
     }
 
     #[test]
@@ -394,8 +804,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(5);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1);
     }
 
     #[test]
@@ -404,8 +814,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(80);
 
-        machine.run();
-        assert_eq!(machine.output[0], 0);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 0);
     }
 
     #[test]
@@ -414,8 +824,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(8);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1);
     }
 
     #[test]
@@ -424,8 +834,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(9);
 
-        machine.run();
-        assert_eq!(machine.output[0], 0);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 0);
     }
 
     #[test]
@@ -434,8 +844,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(5);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1);
     }
 
     #[test]
@@ -444,8 +854,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(9);
 
-        machine.run();
-        assert_eq!(machine.output[0], 0);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 0);
     }
 
     #[test]
@@ -454,8 +864,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(0);
 
-        machine.run();
-        assert_eq!(machine.output[0], 0);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 0);
     }
 
     #[test]
@@ -464,8 +874,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(999);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1);
     }
 
     #[test]
@@ -474,8 +884,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(0);
 
-        machine.run();
-        assert_eq!(machine.output[0], 0);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 0);
     }
 
     #[test]
@@ -484,8 +894,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(999);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1);
     }
 
     #[test]
@@ -496,8 +906,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(7);
 
-        machine.run();
-        assert_eq!(machine.output[0], 999);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 999);
     }
 
     #[test]
@@ -508,8 +918,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(8);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1000);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1000);
     }
 
     #[test]
@@ -520,8 +930,8 @@ mod tests {
         let mut machine = IntcodeMachine::new(tape)
             .with_input(9);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1001);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1001);
     }
 
     #[test]
@@ -529,7 +939,7 @@ mod tests {
         let tape: Vec<i64> = vec![109, 2000, 109, 19, 99];
         let mut machine = IntcodeMachine::new(tape);
 
-        machine.run();
+        machine.run().unwrap();
         assert_eq!(2019, machine.relative_base);
     }
 
@@ -538,9 +948,9 @@ mod tests {
         let tape: Vec<i64> = vec![109, 21, 204, -19, 99];
         let mut machine = IntcodeMachine::new(tape);
 
-        machine.run();
+        let output = machine.run().unwrap();
         assert_eq!(21, machine.relative_base);
-        assert_eq!(machine.output[0], 204);
+        assert_eq!(output[0], 204);
     }
 
     #[test]
@@ -548,8 +958,8 @@ mod tests {
         let tape: Vec<i64> = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
         let mut machine = IntcodeMachine::new(tape.clone());
 
-        machine.run();
-        assert_eq!(machine.output, tape);
+        let output = machine.run().unwrap();
+        assert_eq!(output, tape);
     }
 
     #[test]
@@ -557,8 +967,8 @@ mod tests {
         let tape: Vec<i64> = vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0];
         let mut machine = IntcodeMachine::new(tape);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1219070632396864);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1219070632396864);
     }
 
     #[test]
@@ -566,7 +976,150 @@ mod tests {
         let tape: Vec<i64> = vec![104, 1125899906842624, 99];
         let mut machine = IntcodeMachine::new(tape);
 
-        machine.run();
-        assert_eq!(machine.output[0], 1125899906842624);
+        let output = machine.run().unwrap();
+        assert_eq!(output[0], 1125899906842624);
+    }
+
+    #[test]
+    fn test_unknown_opcode_returns_error() {
+        let tape: Vec<i64> = vec![50, 99];
+        let mut machine = IntcodeMachine::new(tape);
+
+        assert_eq!(machine.run(), Err(ExecutionError::UnknownOpcode(50)));
+    }
+
+    #[test]
+    fn test_unknown_mode_returns_error() {
+        let tape: Vec<i64> = vec![30001, 0, 0, 99];
+        let mut machine = IntcodeMachine::new(tape);
+
+        assert_eq!(machine.run(), Err(ExecutionError::UnknownMode(3)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_run_after_halt_returns_error() {
+        let tape: Vec<i64> = vec![99];
+        let mut machine = IntcodeMachine::new(tape);
+
+        machine.run().unwrap();
+        assert_eq!(machine.run(), Err(ExecutionError::AlreadyHalted));
+    }
+
+    #[test]
+    fn test_operand_past_end_of_tape_returns_error_instead_of_panicking() {
+        let tape: Vec<i64> = vec![1];
+        let mut machine = IntcodeMachine::new(tape);
+
+        assert_eq!(machine.run(), Err(ExecutionError::InvalidAddress(1)));
+    }
+
+    #[test]
+    fn test_negative_positional_destination_returns_error_instead_of_panicking() {
+        let tape: Vec<i64> = vec![1, 5, 6, -5, 99, 2, 3];
+        let mut machine = IntcodeMachine::new(tape);
+
+        assert_eq!(machine.run(), Err(ExecutionError::NegativeAddress(-5)));
+    }
+
+    #[test]
+    fn test_amplifier_chain_should_output_43210() {
+        let tape: Vec<i64> = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        let mut amplifier = Amplifier::new(&tape, &[4, 3, 2, 1, 0], false);
+
+        assert_eq!(amplifier.run(0).unwrap(), 43210);
+    }
+
+    #[test]
+    fn test_decode_multiply_with_mixed_modes() {
+        let tape: Vec<i64> = vec![1002, 4, 3, 4, 33];
+        let machine = IntcodeMachine::new(tape);
+
+        assert_eq!(
+            machine.decode(0).unwrap(),
+            Instruction::Multiply(Mode::Positional, Mode::Immediate, Mode::Positional)
+        );
+    }
+
+    #[test]
+    fn test_decode_halt() {
+        let tape: Vec<i64> = vec![99];
+        let machine = IntcodeMachine::new(tape);
+
+        assert_eq!(machine.decode(0).unwrap(), Instruction::Halt);
+    }
+
+    #[test]
+    fn test_decode_past_end_of_tape_is_invalid_address() {
+        let tape: Vec<i64> = vec![99];
+        let machine = IntcodeMachine::new(tape);
+
+        assert_eq!(machine.decode(1), Err(ExecutionError::InvalidAddress(1)));
+    }
+
+    #[test]
+    fn test_disassemble_renders_add_and_halt() {
+        let tape: Vec<i64> = vec![1, 0, 0, 0, 99];
+        let machine = IntcodeMachine::new(tape);
+
+        assert_eq!(
+            machine.disassemble(),
+            vec![(0, "ADD [0] [0] -> [0]".to_string()), (4, "HLT".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_renders_relative_base_adjustment() {
+        let tape: Vec<i64> = vec![109, 19, 99];
+        let machine = IntcodeMachine::new(tape);
+
+        assert_eq!(
+            machine.disassemble(),
+            vec![(0, "ARB +19".to_string()), (2, "HLT".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_amplifier_feedback_loop_should_output_139629729() {
+        let tape: Vec<i64> = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+            1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let mut amplifier = Amplifier::new(&tape, &[9, 8, 7, 6, 5], true);
+
+        assert_eq!(amplifier.run(0).unwrap(), 139629729);
+    }
+
+    #[test]
+    fn test_step_reports_output_then_halted() {
+        let tape: Vec<i64> = vec![104, 42, 99];
+        let mut machine = IntcodeMachine::new(tape);
+
+        assert_eq!(machine.step().unwrap(), StepResult::Output(42));
+        assert_eq!(machine.step().unwrap(), StepResult::Halted);
+    }
+
+    #[test]
+    fn test_step_reports_needs_input_without_consuming_the_instruction() {
+        let tape: Vec<i64> = vec![3, 0, 99];
+        let mut machine = IntcodeMachine::new(tape);
+
+        assert_eq!(machine.step().unwrap(), StepResult::NeedsInput);
+
+        machine.add_input(7);
+        assert_eq!(machine.step().unwrap(), StepResult::Continue);
+        assert_eq!(machine.step().unwrap(), StepResult::Halted);
+    }
+
+    #[test]
+    fn test_clone_snapshots_independent_state() {
+        let tape: Vec<i64> = vec![104, 1, 99];
+        let mut machine = IntcodeMachine::new(tape);
+        let mut checkpoint = machine.clone();
+
+        machine.run().unwrap();
+        assert!(machine.halted());
+        assert!(!checkpoint.halted());
+
+        assert_eq!(checkpoint.step().unwrap(), StepResult::Output(1));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{ExecutionError, IntcodeMachine, Pipe};
+
+/// Address the NAT listens on; packets sent here are held rather than
+/// delivered, and replayed to address 0 once the network goes idle.
+pub const NAT_ADDRESS: usize = 255;
+
+/// A single `(dest, x, y)` packet observed while routing, for callers that
+/// want to watch traffic as it happens (e.g. to find the first repeated Y
+/// value the NAT resends).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PacketEvent {
+    pub source: usize,
+    pub dest: usize,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// A day-23-style network of identical Intcode machines, each booted with a
+/// numeric address and wired to its own input/output `Pipe`. Packets read
+/// three integers at a time off a machine's output and are routed onto the
+/// input of the addressed machine, or to a NAT register if addressed to
+/// `NAT_ADDRESS`.
+pub struct Network {
+    machines: Vec<IntcodeMachine<Pipe, Pipe>>,
+    inputs: Vec<Pipe>,
+    outputs: Vec<Pipe>,
+    nat: Option<(i64, i64)>,
+}
+
+impl Network {
+    pub fn new(tape: &[i64], size: usize) -> Network {
+        let inputs: Vec<Pipe> = (0..size).map(|_| Pipe::new()).collect();
+        let outputs: Vec<Pipe> = (0..size).map(|_| Pipe::new()).collect();
+
+        for (address, input) in inputs.iter().enumerate() {
+            input.push(address as i64);
+        }
+
+        let machines = inputs
+            .iter()
+            .zip(outputs.iter())
+            .map(|(input, output)| IntcodeMachine::with_io(tape.to_vec(), input.clone(), output.clone()))
+            .collect();
+
+        Network { machines, inputs, outputs, nat: None }
+    }
+
+    /// Runs every machine in round-robin fashion, feeding `-1` to any
+    /// machine whose input is empty instead of letting it block, and
+    /// routing every `(dest, x, y)` packet it produces. `on_packet` is
+    /// called for each packet, in case a caller wants to observe traffic.
+    ///
+    /// When a full round passes with no machine consuming real input or
+    /// producing output, the NAT delivers its stored packet to address 0.
+    /// Returns the first Y value the NAT delivers twice in a row, the
+    /// classic day-23 part-2 stop condition.
+    pub fn run(&mut self, mut on_packet: impl FnMut(PacketEvent)) -> Result<i64, ExecutionError> {
+        let mut last_nat_y: Option<i64> = None;
+
+        loop {
+            let mut idle_round = true;
+
+            for address in 0..self.machines.len() {
+                if self.machines[address].halted() {
+                    continue;
+                }
+
+                if self.inputs[address].is_empty() {
+                    self.inputs[address].push(-1);
+                } else {
+                    idle_round = false;
+                }
+
+                self.machines[address].run_until_blocked()?;
+
+                for (dest, x, y) in self.drain_output(address)? {
+                    idle_round = false;
+                    on_packet(PacketEvent { source: address, dest, x, y });
+
+                    if dest == NAT_ADDRESS {
+                        self.nat = Some((x, y));
+                    } else if let Some(input) = self.inputs.get(dest) {
+                        input.push(x);
+                        input.push(y);
+                    }
+                }
+            }
+
+            if idle_round {
+                if let Some((x, y)) = self.nat {
+                    if last_nat_y == Some(y) {
+                        return Ok(y);
+                    }
+
+                    last_nat_y = Some(y);
+                    self.inputs[0].push(x);
+                    self.inputs[0].push(y);
+                }
+            }
+        }
+    }
+
+    fn drain_output(&self, address: usize) -> Result<Vec<(usize, i64, i64)>, ExecutionError> {
+        let output = &self.outputs[address];
+        let mut packets = Vec::new();
+
+        while let Some(dest) = output.pop() {
+            let x = output.pop().ok_or(ExecutionError::IncompletePacket)?;
+            let y = output.pop().ok_or(ExecutionError::IncompletePacket)?;
+            packets.push((dest as usize, x, y));
+        }
+
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_output_mid_packet_returns_incomplete_packet_error() {
+        // Outputs two values then halts, so the final packet never gets its y.
+        let tape: Vec<i64> = vec![104, 7, 104, 8, 99];
+        let mut network = Network::new(&tape, 1);
+
+        assert_eq!(network.run(|_| {}), Err(ExecutionError::IncompletePacket));
+    }
+
+    #[test]
+    fn test_network_idle_and_nat_repeat_delivery() {
+        let tape: Vec<i64> = vec![
+            3, 100, 1008, 100, 1, 101, 1005, 101, 14, 3, 102, 1105, 1, 9, 104, 255, 104, 0, 104, 42,
+            1105, 1, 9,
+        ];
+        let mut network = Network::new(&tape, 2);
+        let mut packets = Vec::new();
+
+        let repeated_y = network.run(|packet| packets.push(packet)).unwrap();
+
+        assert_eq!(repeated_y, 42);
+        assert!(packets.iter().any(|p| p.dest == NAT_ADDRESS && p.x == 0 && p.y == 42));
+    }
+}